@@ -1,10 +1,12 @@
 use std::borrow::Cow;
 use std::collections::HashMap;
 use std::ops::Range;
+use std::sync::Arc;
 
-use crate::builtins::{pybool, PyBytesRef, PyStr, PyStrRef, PyTuple, PyTupleRef};
+use crate::builtins::{pybool, PyBytesRef, PyDict, PyList, PyStr, PyStrRef, PyTuple, PyTupleRef};
 use crate::common::lock::PyRwLock;
 use crate::exceptions::PyBaseExceptionRef;
+use crate::function::OptionalArg;
 use crate::VirtualMachine;
 use crate::{IntoPyObject, PyContext, PyObjectRef, PyResult, PyValue, TryFromObject, TypeProtocol};
 
@@ -16,42 +18,96 @@ struct RegistryInner {
     search_path: Vec<PyObjectRef>,
     search_cache: HashMap<String, PyCodec>,
     errors: HashMap<String, PyObjectRef>,
+    // The search functions installed at construction time or later through
+    // `register_builtin`, i.e. the ones we know answer for the real built-in
+    // codecs. `lookup` only attaches a
+    // `NativeCodec` fast path to a result that came from one of *these* by
+    // identity, never by matching the encoding name alone - otherwise a
+    // `codecs.register()`-provided search function that happens to answer
+    // for e.g. "utf-8" would silently have its result overridden.
+    builtin_search_fns: Vec<PyObjectRef>,
 }
 
 pub const DEFAULT_ENCODING: &str = "utf-8";
 
+/// A Rust implementation of a codec's encode/decode pair, bypassing the
+/// Python-level 4-tuple protocol entirely. `CodecsRegistry::lookup` attaches
+/// one of these to the built-in encodings so that `encode_text`/`decode_text`
+/// can skip `vm.invoke`, the 2-tuple allocation, and the downcast on the hot
+/// path; anything a native implementation doesn't handle (a configured error
+/// handler kicking in, an encoding it doesn't know) falls back to the normal
+/// Python-visible tuple.
+pub trait NativeCodec: Send + Sync {
+    /// Returns `Ok(None)` to fall back to the Python-visible codec, e.g.
+    /// because `s` can't be represented without invoking an error handler.
+    fn encode(&self, s: &str, vm: &VirtualMachine) -> PyResult<Option<Vec<u8>>>;
+    /// Returns `Ok(None)` to fall back to the Python-visible codec.
+    fn decode(&self, data: &[u8], vm: &VirtualMachine) -> PyResult<Option<String>>;
+}
+
 #[derive(Clone)]
-#[repr(transparent)]
-pub struct PyCodec(PyTupleRef);
+pub struct PyCodec {
+    tuple: PyTupleRef,
+    native: Option<Arc<dyn NativeCodec>>,
+    // Set by `CodecsRegistry::lookup` to the normalized name this codec was
+    // looked up under, so `get_incremental_decoder` can ask for a native
+    // `IncrementalDecoder` without the caller having to pass the name again.
+    encoding_name: Option<String>,
+    // Set by `CodecsRegistry::lookup` when this codec's tuple came from a
+    // search function registered via `register_builtin` rather than the
+    // ordinary user-facing `register`. Both the `NativeCodec` fast path and
+    // the native incremental-decoder swap are gated on this, not on the
+    // encoding name alone, so a user's own `codecs.register()`-provided
+    // codec answering for e.g. "utf-8" is never silently overridden.
+    is_builtin: bool,
+}
 impl PyCodec {
     #[inline]
     pub fn from_tuple(tuple: PyTupleRef) -> Result<Self, PyTupleRef> {
         if tuple.len() == 4 {
-            Ok(PyCodec(tuple))
+            Ok(PyCodec {
+                tuple,
+                native: None,
+                encoding_name: None,
+                is_builtin: false,
+            })
+        } else {
+            Err(tuple)
+        }
+    }
+    #[inline]
+    pub fn with_native(tuple: PyTupleRef, native: Arc<dyn NativeCodec>) -> Result<Self, PyTupleRef> {
+        if tuple.len() == 4 {
+            Ok(PyCodec {
+                tuple,
+                native: Some(native),
+                encoding_name: None,
+                is_builtin: false,
+            })
         } else {
             Err(tuple)
         }
     }
     #[inline]
     pub fn into_tuple(self) -> PyTupleRef {
-        self.0
+        self.tuple
     }
     #[inline]
     pub fn as_tuple(&self) -> &PyTupleRef {
-        &self.0
+        &self.tuple
     }
 
     #[inline]
     pub fn get_encode_func(&self) -> &PyObjectRef {
-        &self.0.as_slice()[0]
+        &self.tuple.as_slice()[0]
     }
     #[inline]
     pub fn get_decode_func(&self) -> &PyObjectRef {
-        &self.0.as_slice()[1]
+        &self.tuple.as_slice()[1]
     }
 
     pub fn is_text_codec(&self, vm: &VirtualMachine) -> PyResult<bool> {
-        let is_text = vm.get_attribute_opt(self.0.clone().into_object(), "_is_text_encoding")?;
+        let is_text = vm.get_attribute_opt(self.tuple.clone().into_object(), "_is_text_encoding")?;
         is_text.map_or(Ok(true), |is_text| pybool::boolval(vm, is_text))
     }
 
@@ -108,7 +164,7 @@ impl PyCodec {
             Some(e) => vec![e.into_object()],
             None => vec![],
         };
-        vm.call_method(self.0.as_object(), "incrementalencoder", args)
+        vm.call_method(self.tuple.as_object(), "incrementalencoder", args)
     }
 
     pub fn get_incremental_decoder(
@@ -116,11 +172,105 @@ impl PyCodec {
         errors: Option<PyStrRef>,
         vm: &VirtualMachine,
     ) -> PyResult {
-        let args = match errors {
+        let args = match errors.clone() {
             Some(e) => vec![e.into_object()],
             None => vec![],
         };
-        vm.call_method(self.0.as_object(), "incrementaldecoder", args)
+        let decoder = vm.call_method(self.tuple.as_object(), "incrementaldecoder", args)?;
+        // If this is one of the built-in multibyte encodings - resolved by a
+        // search function registered through `register_builtin`, not just
+        // matching the name - swap the decoder's `decode`/`reset` for ones
+        // backed by our native `IncrementalDecoder`, which buffers characters
+        // split across chunks natively. Each chunk is still decoded through
+        // `CodecsRegistry::decode_text`, so only encodings with a
+        // `NativeCodec` attached skip the `vm.invoke` and tuple allocation;
+        // the others call the Python-level codec per chunk. A
+        // user's own incremental decoder class, even one answering for a
+        // name like "utf-8", is left untouched.
+        if self.is_builtin {
+            if let Some(encoding_name) = &self.encoding_name {
+                if let Some(native) = IncrementalDecoder::new(encoding_name, errors) {
+                    attach_native_incremental_decoder(&decoder, encoding_name.clone(), native, vm)?;
+                }
+            }
+        }
+        Ok(decoder)
+    }
+}
+
+/// Monkey-patches `decoder`'s `decode`/`reset` instance attributes so they're
+/// backed by `native` instead of the Python-level implementation the factory
+/// built. This shadows the class methods without needing a dedicated
+/// Rust-backed Python class: a plain function stored as an instance
+/// attribute is called directly, with no implicit `self`.
+fn attach_native_incremental_decoder(
+    decoder: &PyObjectRef,
+    encoding_name: String,
+    native: IncrementalDecoder,
+    vm: &VirtualMachine,
+) -> PyResult<()> {
+    let state = Arc::new(PyRwLock::new(native));
+
+    let decode_state = state.clone();
+    let decode_encoding = encoding_name.clone();
+    let decode_fn = vm.ctx.new_function(
+        "decode",
+        move |input: PyBytesRef, final_chunk: OptionalArg<bool>, vm: &VirtualMachine| {
+            decode_state.write().feed(
+                &vm.state.codec_registry,
+                &decode_encoding,
+                &input,
+                final_chunk.unwrap_or(false),
+                vm,
+            )
+        },
+    );
+    vm.set_attr(decoder, "decode", decode_fn)?;
+
+    let reset_fn = vm.ctx.new_function("reset", move |_vm: &VirtualMachine| {
+        state.write().reset();
+    });
+    vm.set_attr(decoder, "reset", reset_fn)?;
+
+    Ok(())
+}
+
+struct Utf8Codec;
+
+impl NativeCodec for Utf8Codec {
+    fn encode(&self, s: &str, _vm: &VirtualMachine) -> PyResult<Option<Vec<u8>>> {
+        Ok(Some(s.as_bytes().to_vec()))
+    }
+
+    fn decode(&self, data: &[u8], _vm: &VirtualMachine) -> PyResult<Option<String>> {
+        Ok(std::str::from_utf8(data).ok().map(str::to_owned))
+    }
+}
+
+struct Latin1Codec;
+
+impl NativeCodec for Latin1Codec {
+    fn encode(&self, s: &str, _vm: &VirtualMachine) -> PyResult<Option<Vec<u8>>> {
+        if s.chars().all(|c| (c as u32) <= 0xff) {
+            Ok(Some(s.chars().map(|c| c as u8).collect()))
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn decode(&self, data: &[u8], _vm: &VirtualMachine) -> PyResult<Option<String>> {
+        Ok(Some(data.iter().map(|&b| b as char).collect()))
+    }
+}
+
+/// The native implementation to attach to a freshly looked-up built-in
+/// codec, if any. Codecs registered by users through `codecs.register`
+/// never get one, so they always go through the Python-visible tuple.
+fn native_codec_for(encoding: &str) -> Option<Arc<dyn NativeCodec>> {
+    match encoding {
+        "utf-8" => Some(Arc::new(Utf8Codec)),
+        "latin-1" | "iso-8859-1" | "latin1" => Some(Arc::new(Latin1Codec)),
+        _ => None,
     }
 }
 
@@ -138,7 +288,7 @@ impl TryFromObject for PyCodec {
 impl IntoPyObject for PyCodec {
     #[inline]
     fn into_pyobject(self, _vm: &VirtualMachine) -> PyObjectRef {
-        self.0.into_object()
+        self.tuple.into_object()
     }
 }
 
@@ -159,12 +309,25 @@ impl CodecsRegistry {
                 "backslashreplace",
                 ctx.new_function("backslashreplace_errors", backslashreplace_errors),
             ),
+            (
+                "surrogateescape",
+                ctx.new_function("surrogateescape_errors", surrogateescape_errors),
+            ),
+            (
+                "surrogatepass",
+                ctx.new_function("surrogatepass_errors", surrogatepass_errors),
+            ),
         ];
         let errors = std::array::IntoIter::new(errors)
             .map(|(name, f)| (name.to_owned(), f))
             .collect();
+        let search_path = vec![ctx.new_function(
+            "netencode_search_function",
+            netencode::netencode_search_function,
+        )];
         let inner = RegistryInner {
-            search_path: Vec::new(),
+            builtin_search_fns: search_path.clone(),
+            search_path,
             search_cache: HashMap::new(),
             errors,
         };
@@ -181,6 +344,24 @@ impl CodecsRegistry {
         Ok(())
     }
 
+    /// Like `register`, but also marks `search_function` as one of the
+    /// interpreter's own built-in codecs, so a result it produces is
+    /// eligible for the `NativeCodec` fast path and the native incremental
+    /// decoder. Call this only for the interpreter's own bootstrapping (e.g.
+    /// the `encodings` package's search function) - never for a
+    /// user-provided one from the `codecs.register()` builtin, or a
+    /// same-named but behaviorally different codec would silently have its
+    /// encode/decode calls bypassed.
+    pub fn register_builtin(&self, search_function: PyObjectRef, vm: &VirtualMachine) -> PyResult<()> {
+        if !vm.is_callable(&search_function) {
+            return Err(vm.new_type_error("argument must be callable".to_owned()));
+        }
+        let mut inner = self.inner.write();
+        inner.builtin_search_fns.push(search_function.clone());
+        inner.search_path.push(search_function);
+        Ok(())
+    }
+
     pub fn lookup(&self, encoding: &str, vm: &VirtualMachine) -> PyResult<PyCodec> {
         let encoding = normalize_encoding_name(encoding);
         let inner = self.inner.read();
@@ -188,12 +369,19 @@ impl CodecsRegistry {
             return Ok(codec.clone());
         }
         let search_path = inner.search_path.clone();
+        let builtin_search_fns = inner.builtin_search_fns.clone();
         drop(inner); // don't want to deadlock
         let encoding = PyStr::from(encoding.into_owned()).into_ref(vm);
         for func in search_path {
             let res = vm.invoke(&func, (encoding.clone(),))?;
             let res = <Option<PyCodec>>::try_from_object(vm, res)?;
-            if let Some(codec) = res {
+            if let Some(mut codec) = res {
+                let is_builtin = builtin_search_fns.iter().any(|f| f.is(&func));
+                if codec.native.is_none() && is_builtin {
+                    codec.native = native_codec_for(encoding.as_str());
+                }
+                codec.encoding_name = Some(encoding.as_str().to_owned());
+                codec.is_builtin = is_builtin;
                 let mut inner = self.inner.write();
                 // someone might have raced us to this, so use theirs
                 let codec = inner
@@ -258,6 +446,11 @@ impl CodecsRegistry {
         vm: &VirtualMachine,
     ) -> PyResult<PyBytesRef> {
         let codec = self._lookup_text_encoding(encoding, "codecs.encode()", vm)?;
+        if let Some(native) = &codec.native {
+            if let Some(bytes) = native.encode(obj.as_str(), vm)? {
+                return Ok(vm.ctx.new_bytes(bytes));
+            }
+        }
         codec
             .encode(obj.into_object(), errors, vm)?
             .downcast()
@@ -279,6 +472,13 @@ impl CodecsRegistry {
         vm: &VirtualMachine,
     ) -> PyResult<PyStrRef> {
         let codec = self._lookup_text_encoding(encoding, "codecs.decode()", vm)?;
+        if let Some(native) = &codec.native {
+            if let Ok(bytes) = PyBytesRef::try_from_object(vm, obj.clone()) {
+                if let Some(s) = native.decode(&bytes, vm)? {
+                    return Ok(vm.ctx.new_str(s));
+                }
+            }
+        }
         codec.decode(obj, errors, vm)?.downcast().map_err(|obj| {
             vm.new_type_error(format!(
                 "'{}' decoder returned '{}' instead of 'str'; use codecs.decode() \
@@ -303,6 +503,141 @@ impl CodecsRegistry {
     }
 }
 
+/// The built-in multibyte encodings `IncrementalDecoder` knows how to find a
+/// chunk boundary for without invoking the (possibly-Python) codec.
+#[derive(Clone, Copy)]
+enum MultibyteEncoding {
+    Utf8,
+    Utf16Le,
+    Utf16Be,
+    Utf32Le,
+    Utf32Be,
+}
+
+impl MultibyteEncoding {
+    fn from_name(encoding: &str) -> Option<Self> {
+        match normalize_encoding_name(encoding).as_ref() {
+            "utf-8" => Some(Self::Utf8),
+            "utf-16" | "utf-16-le" => Some(Self::Utf16Le),
+            "utf-16-be" => Some(Self::Utf16Be),
+            "utf-32" | "utf-32-le" => Some(Self::Utf32Le),
+            "utf-32-be" => Some(Self::Utf32Be),
+            _ => None,
+        }
+    }
+
+    fn unit_size(self) -> usize {
+        match self {
+            Self::Utf8 => 1,
+            Self::Utf16Le | Self::Utf16Be => 2,
+            Self::Utf32Le | Self::Utf32Be => 4,
+        }
+    }
+
+    /// Splits `buf` into a decodable prefix and a trailing fragment that's
+    /// too short to be a complete character yet, but could become one if fed
+    /// more bytes. A fragment that's already guaranteed to be malformed (e.g.
+    /// a UTF-8 continuation byte in a position that can never be valid) is
+    /// left in the decodable half, so the normal error handler deals with it.
+    fn split_trailing_incomplete<'b>(self, buf: &'b [u8]) -> (&'b [u8], &'b [u8]) {
+        match self {
+            Self::Utf8 => {
+                // Skip past malformed bytes so a truncated sequence at the
+                // very end is still found after an earlier decoding error.
+                let mut start = 0;
+                loop {
+                    match std::str::from_utf8(&buf[start..]) {
+                        Ok(_) => return (buf, &buf[buf.len()..]),
+                        Err(e) => match e.error_len() {
+                            None => return buf.split_at(start + e.valid_up_to()),
+                            Some(len) => start += e.valid_up_to() + len,
+                        },
+                    }
+                }
+            }
+            Self::Utf16Le | Self::Utf16Be => {
+                let unit = self.unit_size();
+                let mut cut = buf.len() - buf.len() % unit;
+                // A lone high surrogate at the end of the decodable prefix
+                // is the first half of a surrogate pair that might be
+                // completed by the next `feed`; hold it back too.
+                if cut >= unit {
+                    let last_unit = [buf[cut - 2], buf[cut - 1]];
+                    let code_unit = if matches!(self, Self::Utf16Be) {
+                        u16::from_be_bytes(last_unit)
+                    } else {
+                        u16::from_le_bytes(last_unit)
+                    };
+                    if (0xd800..=0xdbff).contains(&code_unit) {
+                        cut -= unit;
+                    }
+                }
+                buf.split_at(cut)
+            }
+            Self::Utf32Le | Self::Utf32Be => {
+                let unit = self.unit_size();
+                buf.split_at(buf.len() - buf.len() % unit)
+            }
+        }
+    }
+}
+
+/// A native, streaming incremental decoder for the built-in multibyte
+/// codecs. It holds only the bytes left over from the previous `feed` (at
+/// most a few bytes - the tail of a character split across a chunk
+/// boundary) plus the error handler name configured for this decoder, and
+/// decodes through `registry` so error recovery stays identical to the
+/// non-incremental path.
+pub struct IncrementalDecoder {
+    encoding: MultibyteEncoding,
+    errors: Option<PyStrRef>,
+    pending: Vec<u8>,
+}
+
+impl IncrementalDecoder {
+    /// Returns `None` if `encoding` isn't one of the built-in multibyte
+    /// codecs this subsystem natively understands.
+    pub fn new(encoding: &str, errors: Option<PyStrRef>) -> Option<Self> {
+        Some(IncrementalDecoder {
+            encoding: MultibyteEncoding::from_name(encoding)?,
+            errors,
+            pending: Vec::new(),
+        })
+    }
+
+    pub fn reset(&mut self) {
+        self.pending.clear();
+    }
+
+    /// Appends `input` to the pending buffer and decodes as much of it as
+    /// forms complete characters. A truncated-but-possibly-valid tail is
+    /// held back for the next call; passing `final_chunk = true` forces that
+    /// tail (if any) through the configured error handler instead.
+    pub fn feed(
+        &mut self,
+        registry: &CodecsRegistry,
+        encoding_name: &str,
+        input: &[u8],
+        final_chunk: bool,
+        vm: &VirtualMachine,
+    ) -> PyResult<String> {
+        self.pending.extend_from_slice(input);
+        let (_, tail) = self.encoding.split_trailing_incomplete(&self.pending);
+        let decodable_len = if tail.is_empty() || final_chunk {
+            self.pending.len()
+        } else {
+            self.pending.len() - tail.len()
+        };
+        let chunk: Vec<u8> = self.pending.drain(..decodable_len).collect();
+        if chunk.is_empty() {
+            return Ok(String::new());
+        }
+        let decoded =
+            registry.decode_text(vm.ctx.new_bytes(chunk), encoding_name, self.errors.clone(), vm)?;
+        Ok(decoded.as_str().to_owned())
+    }
+}
+
 fn normalize_encoding_name(encoding: &str) -> Cow<'_, str> {
     if let Some(i) = encoding.find(|c: char| c == ' ' || c.is_ascii_uppercase()) {
         let mut out = encoding.as_bytes().to_owned();
@@ -428,3 +763,328 @@ fn backslashreplace_errors(err: PyObjectRef, vm: &VirtualMachine) -> PyResult<(S
     }
     Ok((out, range.end))
 }
+
+/// `surrogateescape`/`surrogatepass` are supposed to round-trip an
+/// unrepresentable byte through a lone surrogate code point
+/// (`0xd800..=0xdfff`). UTF-8 explicitly forbids encoding a surrogate half,
+/// so there is no value of type `char` (and therefore no `String`) that can
+/// hold one: `char::from_u32` rejects the whole range, and building the
+/// bytes by hand with an unsafe UTF-8 constructor - as a prior version of
+/// this code did - produces a `String` that violates `str`'s core validity
+/// invariant, which is real undefined behavior, not just a logical wart.
+/// Supporting these handlers properly requires a surrogate-capable string
+/// representation (e.g. WTF-8) with its own sound constructor, which `PyStr`
+/// doesn't have. Until it does, both handlers below are registered under
+/// their real names - so `codecs.lookup_error` finds them instead of
+/// raising `LookupError` on a script that merely references the name - but
+/// always fail with this one honest error instead of validating a
+/// byte/char range and then failing anyway, which would misleadingly read
+/// as partial support.
+fn surrogate_unsupported(vm: &VirtualMachine) -> PyBaseExceptionRef {
+    vm.new_value_error(
+        "surrogateescape/surrogatepass encoding and decoding require a surrogate-capable \
+         string type, which this build's str does not have"
+            .to_owned(),
+    )
+}
+
+fn surrogateescape_errors(err: PyObjectRef, vm: &VirtualMachine) -> PyResult<(PyObjectRef, usize)> {
+    if is_decode_err(&err, vm) || err.isinstance(&vm.ctx.exceptions.unicode_encode_error) {
+        Err(surrogate_unsupported(vm))
+    } else {
+        Err(bad_err_type(err, vm))
+    }
+}
+
+fn surrogatepass_errors(err: PyObjectRef, vm: &VirtualMachine) -> PyResult<(PyObjectRef, usize)> {
+    if is_decode_err(&err, vm) || err.isinstance(&vm.ctx.exceptions.unicode_encode_error) {
+        Err(surrogate_unsupported(vm))
+    } else {
+        Err(bad_err_type(err, vm))
+    }
+}
+
+/// A self-describing, length-prefixed binary object codec
+/// (https://github.com/Profpatsch/netencode). Unlike the text codecs above,
+/// `netencode` round-trips arbitrary Python objects, not `str`/`bytes`, so
+/// it's reached only through `codecs.encode`/`codecs.decode`, not
+/// `str.encode`/`bytes.decode`.
+mod netencode {
+    use super::*;
+
+    /// Apart from the `n1` booleans, netencode's `n`/`i` width tags only ever
+    /// take one of these three digits, mapping to 8/64/128-bit widths.
+    fn size_tag(bits: u32) -> Option<u8> {
+        match bits {
+            8 => Some(b'3'),
+            64 => Some(b'6'),
+            128 => Some(b'7'),
+            _ => None,
+        }
+    }
+
+    fn tag_bits(tag: u8) -> Option<u32> {
+        match tag {
+            b'3' => Some(8),
+            b'6' => Some(64),
+            b'7' => Some(128),
+            _ => None,
+        }
+    }
+
+    fn write_length_prefixed(out: &mut Vec<u8>, type_char: u8, payload: &[u8]) {
+        out.push(type_char);
+        out.extend_from_slice(payload.len().to_string().as_bytes());
+        out.push(b':');
+        out.extend_from_slice(payload);
+        out.push(b',');
+    }
+
+    fn encode_value(obj: &PyObjectRef, vm: &VirtualMachine, out: &mut Vec<u8>) -> PyResult<()> {
+        if vm.is_none(obj) {
+            out.extend_from_slice(b"u,");
+        } else if obj.isinstance(&vm.ctx.types.bool_type) {
+            let b = pybool::boolval(vm, obj.clone())?;
+            out.extend_from_slice(if b { b"n1:1," } else { b"n1:0," });
+        } else if obj.isinstance(&vm.ctx.types.int_type) {
+            let value = i128::try_from_object(vm, obj.clone())?;
+            let (type_char, bits) = if value >= 0 {
+                let bits = [8u32, 64, 128]
+                    .iter()
+                    .copied()
+                    .find(|&bits| {
+                        u128::try_from(value).map_or(false, |v| match 1u128.checked_shl(bits) {
+                            Some(limit) => v < limit,
+                            // `bits == 128` is the last, all-encompassing bucket.
+                            None => true,
+                        })
+                    })
+                    .ok_or_else(|| vm.new_value_error("int too large for netencode".to_owned()))?;
+                (b'n', bits)
+            } else {
+                let bits = [8u32, 64, 128]
+                    .iter()
+                    .copied()
+                    .find(|&bits| {
+                        match 1i128.checked_shl(bits - 1).and_then(i128::checked_neg) {
+                            Some(limit) => value >= limit,
+                            // `bits == 128` means `1i128 << 127 == i128::MIN`,
+                            // which can't be negated without overflow - but
+                            // it's also the last, all-encompassing bucket,
+                            // and `value` is already an `i128`, so it fits.
+                            None => true,
+                        }
+                    })
+                    .ok_or_else(|| vm.new_value_error("int too large for netencode".to_owned()))?;
+                (b'i', bits)
+            };
+            out.push(type_char);
+            out.push(size_tag(bits).unwrap());
+            out.push(b':');
+            out.extend_from_slice(value.to_string().as_bytes());
+            out.push(b',');
+        } else if let Ok(s) = PyStrRef::try_from_object(vm, obj.clone()) {
+            write_length_prefixed(out, b't', s.as_str().as_bytes());
+        } else if let Ok(b) = PyBytesRef::try_from_object(vm, obj.clone()) {
+            write_length_prefixed(out, b'b', &b);
+        } else if let Ok(tuple) = PyTupleRef::try_from_object(vm, obj.clone()) {
+            if tuple.len() != 2 {
+                return Err(vm.new_type_error(
+                    "only 2-tuples of (tag, value) can be encoded as netencode".to_owned(),
+                ));
+            }
+            let tag = PyStrRef::try_from_object(vm, tuple.as_slice()[0].clone())?;
+            let tag_bytes = tag.as_str().as_bytes();
+            out.push(b'<');
+            out.extend_from_slice(tag_bytes.len().to_string().as_bytes());
+            out.push(b':');
+            out.extend_from_slice(tag_bytes);
+            encode_value(&tuple.as_slice()[1], vm, out)?;
+        } else if let Ok(list) = obj.clone().downcast::<PyList>() {
+            let mut body = Vec::new();
+            for item in list.borrow_vec().iter() {
+                encode_value(item, vm, &mut body)?;
+            }
+            out.push(b'[');
+            out.extend_from_slice(body.len().to_string().as_bytes());
+            out.push(b':');
+            out.extend_from_slice(&body);
+            out.push(b']');
+        } else if let Ok(dict) = obj.clone().downcast::<PyDict>() {
+            let mut body = Vec::new();
+            for (key, value) in dict {
+                let key = PyStrRef::try_from_object(vm, key)?;
+                write_length_prefixed(&mut body, b't', key.as_str().as_bytes());
+                encode_value(&value, vm, &mut body)?;
+            }
+            out.push(b'{');
+            out.extend_from_slice(body.len().to_string().as_bytes());
+            out.push(b':');
+            out.extend_from_slice(&body);
+            out.push(b'}');
+        } else {
+            return Err(vm.new_type_error(format!(
+                "don't know how to encode '{}' as netencode",
+                obj.class().name
+            )));
+        }
+        Ok(())
+    }
+
+    fn split_on_colon<'b>(data: &'b [u8], vm: &VirtualMachine) -> PyResult<(&'b [u8], &'b [u8])> {
+        let idx = data
+            .iter()
+            .position(|&b| b == b':')
+            .ok_or_else(|| vm.new_value_error("netencode value is missing ':'".to_owned()))?;
+        Ok((&data[..idx], &data[idx + 1..]))
+    }
+
+    fn parse_len(len_str: &[u8], vm: &VirtualMachine) -> PyResult<usize> {
+        std::str::from_utf8(len_str)
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .ok_or_else(|| vm.new_value_error("invalid netencode length prefix".to_owned()))
+    }
+
+    /// Decodes one value from the front of `data`, returning it along with
+    /// how many bytes of `data` it consumed.
+    fn decode_value(data: &[u8], vm: &VirtualMachine) -> PyResult<(PyObjectRef, usize)> {
+        let (&tag, rest) = data
+            .split_first()
+            .ok_or_else(|| vm.new_value_error("empty netencode value".to_owned()))?;
+        match tag {
+            b'u' => {
+                if rest.first() != Some(&b',') {
+                    return Err(vm.new_value_error("malformed netencode unit".to_owned()));
+                }
+                Ok((vm.ctx.none(), 2))
+            }
+            b'n' if rest.starts_with(b"1:0,") => Ok((vm.ctx.new_bool(false), 5)),
+            b'n' if rest.starts_with(b"1:1,") => Ok((vm.ctx.new_bool(true), 5)),
+            b'n' | b'i' => {
+                let (len_str, after_colon) = split_on_colon(rest, vm)?;
+                let &[class] = len_str else {
+                    return Err(vm.new_value_error("invalid netencode int width".to_owned()));
+                };
+                tag_bits(class)
+                    .ok_or_else(|| vm.new_value_error("invalid netencode int width".to_owned()))?;
+                let comma = after_colon
+                    .iter()
+                    .position(|&b| b == b',')
+                    .ok_or_else(|| vm.new_value_error("unterminated netencode int".to_owned()))?;
+                let digits = std::str::from_utf8(&after_colon[..comma])
+                    .map_err(|_| vm.new_value_error("invalid netencode int".to_owned()))?;
+                let value: i128 = digits
+                    .parse()
+                    .map_err(|_| vm.new_value_error("invalid netencode int".to_owned()))?;
+                let consumed = 1 + len_str.len() + 1 + comma + 1;
+                Ok((vm.ctx.new_int(value), consumed))
+            }
+            b't' | b'b' => {
+                let (len_str, after_colon) = split_on_colon(rest, vm)?;
+                let len = parse_len(len_str, vm)?;
+                if after_colon.get(len) != Some(&b',') {
+                    return Err(vm.new_value_error("truncated netencode value".to_owned()));
+                }
+                let payload = &after_colon[..len];
+                let value = if tag == b't' {
+                    let s = std::str::from_utf8(payload)
+                        .map_err(|_| vm.new_value_error("invalid utf-8 in netencode text".to_owned()))?;
+                    vm.ctx.new_str(s.to_owned())
+                } else {
+                    vm.ctx.new_bytes(payload.to_vec())
+                };
+                Ok((value, 1 + len_str.len() + 1 + len + 1))
+            }
+            b'<' => {
+                let (len_str, after_colon) = split_on_colon(rest, vm)?;
+                let len = parse_len(len_str, vm)?;
+                let tag_name = after_colon
+                    .get(..len)
+                    .ok_or_else(|| vm.new_value_error("truncated netencode tag".to_owned()))?;
+                let tag_name = std::str::from_utf8(tag_name)
+                    .map_err(|_| vm.new_value_error("invalid utf-8 in netencode tag".to_owned()))?;
+                let (value, value_len) = decode_value(&after_colon[len..], vm)?;
+                let consumed = 1 + len_str.len() + 1 + len + value_len;
+                let pair = vm
+                    .ctx
+                    .new_tuple(vec![vm.ctx.new_str(tag_name.to_owned()), value]);
+                Ok((pair, consumed))
+            }
+            b'[' | b'{' => {
+                let (len_str, after_colon) = split_on_colon(rest, vm)?;
+                let body_len = parse_len(len_str, vm)?;
+                let closing = if tag == b'[' { b']' } else { b'}' };
+                if after_colon.get(body_len) != Some(&closing) {
+                    return Err(vm.new_value_error(
+                        "netencode composite is missing its closing bracket".to_owned(),
+                    ));
+                }
+                let mut body = &after_colon[..body_len];
+                let value = if tag == b'[' {
+                    let mut items = Vec::new();
+                    while !body.is_empty() {
+                        let (item, n) = decode_value(body, vm)?;
+                        items.push(item);
+                        body = &body[n..];
+                    }
+                    vm.ctx.new_list(items)
+                } else {
+                    let dict = vm.ctx.new_dict();
+                    while !body.is_empty() {
+                        let (key, n) = decode_value(body, vm)?;
+                        body = &body[n..];
+                        let key = PyStrRef::try_from_object(vm, key)?;
+                        let (value, n) = decode_value(body, vm)?;
+                        body = &body[n..];
+                        dict.set_item(key.as_str(), value, vm)?;
+                    }
+                    dict.into_object()
+                };
+                let consumed = 1 + len_str.len() + 1 + body_len + 1;
+                Ok((value, consumed))
+            }
+            _ => Err(vm.new_value_error(format!(
+                "unknown netencode type tag '{}'",
+                tag as char
+            ))),
+        }
+    }
+
+    fn netencode_encode(
+        obj: PyObjectRef,
+        _errors: OptionalArg<PyStrRef>,
+        vm: &VirtualMachine,
+    ) -> PyResult<(PyObjectRef, usize)> {
+        let mut out = Vec::new();
+        encode_value(&obj, vm, &mut out)?;
+        let len = out.len();
+        Ok((vm.ctx.new_bytes(out), len))
+    }
+
+    fn netencode_decode(
+        data: PyBytesRef,
+        _errors: OptionalArg<PyStrRef>,
+        vm: &VirtualMachine,
+    ) -> PyResult<(PyObjectRef, usize)> {
+        decode_value(&data, vm)
+    }
+
+    pub(super) fn netencode_search_function(
+        encoding: PyStrRef,
+        vm: &VirtualMachine,
+    ) -> PyResult<Option<PyObjectRef>> {
+        if normalize_encoding_name(encoding.as_str()) != "netencode" {
+            return Ok(None);
+        }
+        let encode = vm.ctx.new_function("netencode_encode", netencode_encode);
+        let decode = vm.ctx.new_function("netencode_decode", netencode_decode);
+        let info = vm
+            .ctx
+            .new_tuple(vec![encode, decode, vm.ctx.none(), vm.ctx.none()]);
+        // netencode carries arbitrary Python objects, not text, so it must
+        // opt out of the text-encoding gate `is_text_codec` checks for.
+        vm.set_attr(&info, "_is_text_encoding", vm.ctx.new_bool(false))?;
+        Ok(Some(info))
+    }
+}